@@ -1,42 +1,410 @@
 use image::{GenericImageView};
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 
-fn get_str_ascii(intent :u8)-> &'static str {
-    let index = intent / 32;
-    let ascii = [" ", ".", ",", "-", "~", "+", "=", "@"];
-    return ascii[index as usize];
+// Collapses a pixel to a 0-255 luma value using the requested formula.
+// Computed in float so the weighted modes stay accurate before quantizing.
+fn get_luma(r: u8, g: u8, b: u8, luma_mode: &str) -> PyResult<u8> {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let luma = match luma_mode {
+        "max" => r.max(g).max(b),
+        "average" => (r + g + b) / 3.0,
+        "rec601" => 0.299 * r + 0.587 * g + 0.114 * b,
+        "rec709" => 0.2126 * r + 0.7152 * g + 0.0722 * b,
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "unknown luma_mode: {luma_mode:?} (expected \"max\", \"average\", \"rec601\" or \"rec709\")"
+            )))
+        }
+    };
+    Ok(luma.round() as u8)
 }
 
-#[pyfunction]
-fn get_image(dir: &str, scale: u32) -> PyResult<Vec<Vec<&str>>> {
-    let img = image::open(dir).unwrap();
-    let (width,height) = img.dimensions();
-    let mut canvas: Vec<Vec<&str>> = Vec::new();
-
-    for y in 0..height {
-        let mut row: Vec<&str> = Vec::new();
-        for x in 0..width {
-            if y % (scale * 2) == 0 && x % scale == 0 {
-                let pix = img.get_pixel(x, y);
-                let mut intent = pix[0] / 3 + pix[1] / 3 + pix[2] / 3;
-                if pix[3] == 0 {
-                    intent = 0;
-                }
-                row.push(get_str_ascii(intent));
+// Splits a ramp string into its glyphs, respecting multi-byte chars.
+fn ramp_glyphs(ramp: &str) -> Vec<&str> {
+    ramp.char_indices()
+        .map(|(i, c)| &ramp[i..i + c.len_utf8()])
+        .collect()
+}
+
+// Maps a 0-255 luma to a glyph index, applying gamma and invert first and
+// clamping to the top bucket so any ramp length is safe. `ramp_len` must be
+// non-zero; callers are expected to reject an empty ramp before this point.
+fn get_ramp_index(luma: u8, invert: bool, gamma: f32, ramp_len: usize) -> usize {
+    if ramp_len == 0 {
+        return 0;
+    }
+    let mut level = luma as f32 / 255.0;
+    if gamma != 1.0 {
+        level = level.powf(gamma);
+    }
+    if invert {
+        level = 1.0 - level;
+    }
+    let index = (level * ramp_len as f32) as usize;
+    index.min(ramp_len - 1)
+}
+
+// Quantizes a channel into the xterm 256-color 6x6x6 cube.
+fn to_cube_level(channel: u8) -> u8 {
+    (channel as u16 * 5 / 255) as u8
+}
+
+// Wraps a glyph in the ANSI escape sequence for the requested color mode.
+fn colorize(glyph: &str, r: u8, g: u8, b: u8, color: &str) -> PyResult<String> {
+    let cell = match color {
+        "none" => glyph.to_string(),
+        "truecolor" => format!("\x1b[38;2;{};{};{}m{}\x1b[0m", r, g, b, glyph),
+        "ansi256" => {
+            let code = 16 + 36 * to_cube_level(r) + 6 * to_cube_level(g) + to_cube_level(b);
+            format!("\x1b[38;5;{}m{}\x1b[0m", code, glyph)
+        }
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "unknown color mode: {color:?} (expected \"none\", \"ansi256\" or \"truecolor\")"
+            )))
+        }
+    };
+    Ok(cell)
+}
+
+// Averages every pixel inside a source block, treating alpha==0 pixels as
+// background (0,0,0) rather than excluding them from the divisor.
+fn average_block(
+    img: &image::DynamicImage,
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+) -> (u8, u8, u8) {
+    let (mut sum_r, mut sum_g, mut sum_b) = (0u64, 0u64, 0u64);
+    let mut count = 0u64;
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let pix = img.get_pixel(x, y);
+            if pix[3] != 0 {
+                sum_r += pix[0] as u64;
+                sum_g += pix[1] as u64;
+                sum_b += pix[2] as u64;
             }
+            count += 1;
         }
-        if y % (scale * 2) == 0 {
-            row.push("");
+    }
+    if count == 0 {
+        return (0, 0, 0);
+    }
+    ((sum_r / count) as u8, (sum_g / count) as u8, (sum_b / count) as u8)
+}
+
+#[pyfunction]
+#[pyo3(signature = (dir, scale, luma_mode="average", ramp=" .,-~+=@", invert=false, gamma=1.0, color="none", char_aspect=2))]
+#[allow(clippy::too_many_arguments)]
+fn get_image(
+    dir: &str,
+    scale: u32,
+    luma_mode: &str,
+    ramp: &str,
+    invert: bool,
+    gamma: f32,
+    color: &str,
+    char_aspect: u32,
+) -> PyResult<Vec<Vec<String>>> {
+    if ramp.is_empty() {
+        return Err(PyValueError::new_err("ramp must not be empty"));
+    }
+    if scale == 0 {
+        return Err(PyValueError::new_err("scale must be at least 1"));
+    }
+    if char_aspect == 0 {
+        return Err(PyValueError::new_err("char_aspect must be at least 1"));
+    }
+    let img = image::open(dir).unwrap();
+    let (width, height) = img.dimensions();
+    let glyphs = ramp_glyphs(ramp);
+    let block_height = scale * char_aspect;
+    let out_width = width.div_ceil(scale);
+    let out_height = height.div_ceil(block_height);
+    let mut canvas: Vec<Vec<String>> = Vec::new();
+
+    for cy in 0..out_height {
+        let mut row: Vec<String> = Vec::new();
+        let y0 = cy * block_height;
+        let y1 = (y0 + block_height).min(height);
+        for cx in 0..out_width {
+            let x0 = cx * scale;
+            let x1 = (x0 + scale).min(width);
+            let (r, g, b) = average_block(&img, x0, y0, x1, y1);
+            let intent = get_luma(r, g, b, luma_mode)?;
+            let index = get_ramp_index(intent, invert, gamma, glyphs.len());
+            row.push(colorize(glyphs[index], r, g, b, color)?);
         }
+        row.push(String::new());
         canvas.push(row);
     }
 
     Ok(canvas)
 }
 
+// A fixed-size grid of characters that geometric primitives are stamped onto.
+#[pyclass]
+struct Canvas {
+    #[pyo3(get)]
+    width: usize,
+    #[pyo3(get)]
+    height: usize,
+    #[pyo3(get)]
+    fill: char,
+    grid: Vec<Vec<char>>,
+}
+
+impl Canvas {
+    // Writes a glyph, clipping anything outside the canvas bounds.
+    fn set(&mut self, x: isize, y: isize, glyph: char) {
+        if x < 0 || y < 0 {
+            return;
+        }
+        let (x, y) = (x as usize, y as usize);
+        if x < self.width && y < self.height {
+            self.grid[y][x] = glyph;
+        }
+    }
+}
+
+#[pymethods]
+impl Canvas {
+    #[new]
+    fn new(width: usize, height: usize, fill: char) -> Self {
+        Canvas {
+            width,
+            height,
+            fill,
+            grid: vec![vec![fill; width]; height],
+        }
+    }
+
+    #[pyo3(signature = (x, y, glyph='#'))]
+    fn add_point(&mut self, x: isize, y: isize, glyph: char) {
+        self.set(x, y, glyph);
+    }
+
+    #[pyo3(signature = (top_left_x, top_left_y, w, h, glyph='#'))]
+    fn add_rect(&mut self, top_left_x: isize, top_left_y: isize, w: isize, h: isize, glyph: char) {
+        if w <= 0 || h <= 0 {
+            return;
+        }
+        for x in top_left_x..top_left_x + w {
+            self.set(x, top_left_y, glyph);
+            self.set(x, top_left_y + h - 1, glyph);
+        }
+        for y in top_left_y..top_left_y + h {
+            self.set(top_left_x, y, glyph);
+            self.set(top_left_x + w - 1, y, glyph);
+        }
+    }
+
+    // Bresenham's line algorithm.
+    #[pyo3(signature = (x0, y0, x1, y1, glyph='#'))]
+    fn add_line(&mut self, x0: isize, y0: isize, x1: isize, y1: isize, glyph: char) {
+        let (mut x, mut y) = (x0, y0);
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let sx = if x0 < x1 { 1 } else { -1 };
+        let sy = if y0 < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+
+        loop {
+            self.set(x, y, glyph);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    // Exposed to Python as `Canvas.to_string()`; Display isn't visible there,
+    // so the inherent method is intentional.
+    #[allow(clippy::inherent_to_string)]
+    fn to_string(&self) -> String {
+        self.grid
+            .iter()
+            .map(|row| row.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
 /// A Python module implemented in Rust.
 #[pymodule]
 fn ascii_art(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(get_image, m)?)?;
+    m.add_class::<Canvas>()?;
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    fn image_from_pixels(width: u32, height: u32, pixels: &[[u8; 4]]) -> DynamicImage {
+        let mut img = RgbaImage::new(width, height);
+        for (i, pixel) in pixels.iter().enumerate() {
+            let (x, y) = (i as u32 % width, i as u32 / width);
+            img.put_pixel(x, y, Rgba(*pixel));
+        }
+        DynamicImage::ImageRgba8(img)
+    }
+
+    #[test]
+    fn average_block_averages_every_pixel_in_a_multi_pixel_block() {
+        let img = image_from_pixels(
+            2,
+            2,
+            &[
+                [0, 0, 0, 255],
+                [100, 100, 100, 255],
+                [200, 200, 200, 255],
+                [255, 255, 255, 255],
+            ],
+        );
+        assert_eq!(average_block(&img, 0, 0, 2, 2), (138, 138, 138));
+    }
+
+    #[test]
+    fn average_block_treats_transparent_pixels_as_black_but_still_counts_them() {
+        let img = image_from_pixels(2, 1, &[[255, 255, 255, 255], [0, 0, 0, 0]]);
+        assert_eq!(average_block(&img, 0, 0, 2, 1), (127, 127, 127));
+    }
+
+    #[test]
+    fn average_block_only_covers_the_requested_sub_region() {
+        let img = image_from_pixels(3, 1, &[[0, 0, 0, 255], [90, 90, 90, 255], [255, 255, 255, 255]]);
+        assert_eq!(average_block(&img, 0, 0, 2, 1), (45, 45, 45));
+    }
+
+    #[test]
+    fn luma_max_picks_the_brightest_channel() {
+        assert_eq!(get_luma(10, 200, 50, "max").unwrap(), 200);
+    }
+
+    #[test]
+    fn luma_average_is_the_flat_mean() {
+        assert_eq!(get_luma(10, 200, 50, "average").unwrap(), 87);
+    }
+
+    #[test]
+    fn luma_rec601_weights_green_heaviest() {
+        assert_eq!(get_luma(10, 200, 50, "rec601").unwrap(), 126);
+    }
+
+    #[test]
+    fn luma_rec709_weights_green_even_more_heavily() {
+        assert_eq!(get_luma(10, 200, 50, "rec709").unwrap(), 149);
+    }
+
+    #[test]
+    fn luma_rejects_unknown_mode() {
+        assert!(get_luma(10, 200, 50, "bogus").is_err());
+    }
+
+    #[test]
+    fn cube_level_quantizes_full_range_into_six_steps() {
+        assert_eq!(to_cube_level(0), 0);
+        assert_eq!(to_cube_level(128), 2);
+        assert_eq!(to_cube_level(255), 5);
+    }
+
+    #[test]
+    fn colorize_none_passes_the_glyph_through_unchanged() {
+        assert_eq!(colorize("X", 10, 20, 30, "none").unwrap(), "X");
+    }
+
+    #[test]
+    fn colorize_truecolor_emits_a_24_bit_escape_sequence() {
+        assert_eq!(
+            colorize("X", 10, 20, 30, "truecolor").unwrap(),
+            "\x1b[38;2;10;20;30mX\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn colorize_ansi256_quantizes_into_the_xterm_cube() {
+        assert_eq!(colorize("X", 0, 0, 0, "ansi256").unwrap(), "\x1b[38;5;16mX\x1b[0m");
+        assert_eq!(
+            colorize("X", 255, 255, 255, "ansi256").unwrap(),
+            "\x1b[38;5;231mX\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn colorize_rejects_unknown_color_mode() {
+        assert!(colorize("X", 10, 20, 30, "bogus").is_err());
+    }
+
+    #[test]
+    fn ramp_index_clamps_to_last_glyph_at_full_brightness() {
+        assert_eq!(get_ramp_index(255, false, 1.0, 8), 7);
+    }
+
+    #[test]
+    fn ramp_index_stays_in_bounds_for_every_luma_and_ramp_len() {
+        for ramp_len in 1..=16 {
+            for luma in 0..=255u8 {
+                let index = get_ramp_index(luma, false, 1.0, ramp_len);
+                assert!(index < ramp_len);
+            }
+        }
+    }
+
+    #[test]
+    fn ramp_index_invert_flips_dark_and_light() {
+        assert_eq!(get_ramp_index(0, true, 1.0, 8), 7);
+        assert_eq!(get_ramp_index(255, true, 1.0, 8), 0);
+    }
+
+    #[test]
+    fn add_point_clips_out_of_bounds_coordinates() {
+        let mut canvas = Canvas::new(3, 3, ' ');
+        canvas.add_point(-1, 0, '#');
+        canvas.add_point(0, -1, '#');
+        canvas.add_point(3, 0, '#');
+        canvas.add_point(0, 3, '#');
+        assert_eq!(canvas.to_string(), "   \n   \n   ");
+    }
+
+    #[test]
+    fn add_rect_draws_a_hollow_outline() {
+        let mut canvas = Canvas::new(4, 4, '.');
+        canvas.add_rect(0, 0, 4, 4, '#');
+        assert_eq!(canvas.to_string(), "####\n#..#\n#..#\n####");
+    }
+
+    #[test]
+    fn add_rect_clips_edges_outside_canvas_bounds() {
+        let mut canvas = Canvas::new(3, 3, '.');
+        canvas.add_rect(1, 1, 5, 5, '#');
+        assert_eq!(canvas.to_string(), "...\n.##\n.#.");
+    }
+
+    #[test]
+    fn add_line_draws_diagonal_via_bresenham() {
+        let mut canvas = Canvas::new(4, 4, '.');
+        canvas.add_line(0, 0, 3, 3, '#');
+        assert_eq!(canvas.to_string(), "#...\n.#..\n..#.\n...#");
+    }
+
+    #[test]
+    fn add_line_clips_endpoints_outside_canvas_bounds() {
+        let mut canvas = Canvas::new(3, 3, '.');
+        canvas.add_line(-2, 1, 5, 1, '#');
+        assert_eq!(canvas.to_string(), "...\n###\n...");
+    }
+}